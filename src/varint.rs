@@ -6,12 +6,40 @@ pub trait Varint: FromPrimitive + ToPrimitive {
     fn varlen(&self) -> uint;
 }
 
+pub trait SignedVarint: FromPrimitive + ToPrimitive {
+    fn varlen(&self) -> uint;
+}
+
 pub trait VarintReader {
     fn read_le_varint<V: Varint>(&mut self) -> IoResult<V>;
+
+    fn read_le_signed_varint<V: SignedVarint>(&mut self) -> IoResult<V>;
+
+    // Like `read_le_varint`, but rejects overlong (non-minimal) encodings,
+    // e.g. `[0x80, 0x00]` decoding to `0` instead of the minimal `[0x00]`.
+    fn read_le_varint_strict<V: Varint>(&mut self) -> IoResult<V>;
+
+    // Reads a varint length prefix followed by that many bytes, refusing to
+    // allocate more than `max` bytes for a hostile length prefix.
+    fn read_le_length_delimited(&mut self, max: uint) -> IoResult<Vec<u8>>;
+
+    // Reads a protobuf-style field tag, returning `(field_number, wire_type)`.
+    fn read_le_tag(&mut self) -> IoResult<(u32, u8)>;
 }
 
 pub trait VarintWriter {
     fn write_le_varint<V: Varint>(&mut self, x: V) -> IoResult<uint>;
+
+    fn write_le_signed_varint<V: SignedVarint>(&mut self, x: V) -> IoResult<uint>;
+
+    fn write_le_length_delimited(&mut self, bytes: &[u8]) -> IoResult<uint>;
+
+    fn write_le_tag(&mut self, field_number: u32, wire_type: u8) -> IoResult<uint>;
+
+    // Writes every varint in `xs` in sequence and returns the total number of
+    // bytes written. The total is computed up front by summing `varlen` over
+    // `xs`; it is returned to the caller only, not passed to the writer.
+    fn write_le_varint_slice<V: Varint + Copy>(&mut self, xs: &[V]) -> IoResult<uint>;
 }
 
 fn varint_length(mut x: u64) -> uint {
@@ -53,12 +81,68 @@ impl Varint for u64 {
     }
 }
 
+// ZigZag-maps a signed value onto the unsigned range so that small-magnitude
+// negatives stay short, mirroring protobuf's sint32/sint64 encoding.
+fn zigzag_encode(n: i64) -> u64 {
+    ((n << 1) ^ (n >> 63)) as u64
+}
+
+fn zigzag_decode(zz: u64) -> i64 {
+    ((zz >> 1) as i64) ^ -((zz & 1) as i64)
+}
+
+fn zigzag_length(n: i64) -> uint {
+    varint_length(zigzag_encode(n))
+}
+
+impl SignedVarint for i8 {
+    fn varlen(&self) -> uint {
+        zigzag_length(*self as i64)
+    }
+}
+
+impl SignedVarint for i16 {
+    fn varlen(&self) -> uint {
+        zigzag_length(*self as i64)
+    }
+}
+
+impl SignedVarint for i32 {
+    fn varlen(&self) -> uint {
+        zigzag_length(*self as i64)
+    }
+}
+
+impl SignedVarint for i64 {
+    fn varlen(&self) -> uint {
+        zigzag_length(*self)
+    }
+}
+
 static OWERFLOW_ERROR: IoError = IoError {
     kind: InvalidInput,
     desc: "owerflow",
     detail: None,
 };
 
+static NON_MINIMAL_ENCODING_ERROR: IoError = IoError {
+    kind: InvalidInput,
+    desc: "varint is not minimally encoded",
+    detail: None,
+};
+
+static LENGTH_EXCEEDS_MAXIMUM_ERROR: IoError = IoError {
+    kind: InvalidInput,
+    desc: "length-delimited value exceeds the allowed maximum",
+    detail: None,
+};
+
+static INVALID_TAG_ERROR: IoError = IoError {
+    kind: InvalidInput,
+    desc: "field number or wire type out of range",
+    detail: None,
+};
+
 
 impl<R> VarintReader for R where R: Reader {
     fn read_le_varint<V: Varint>(&mut self) -> IoResult<V> {
@@ -69,6 +153,37 @@ impl<R> VarintReader for R where R: Reader {
             }
         })
     }
+
+    fn read_le_signed_varint<V: SignedVarint>(&mut self) -> IoResult<V> {
+        read_le_varint(self).and_then(|zz| {
+            match num::from_i64(zigzag_decode(zz)) {
+                Some(x) => Ok(x),
+                None => Err(OWERFLOW_ERROR.clone()),
+            }
+        })
+    }
+
+    fn read_le_varint_strict<V: Varint>(&mut self) -> IoResult<V> {
+        read_le_varint_strict(self).and_then(|x| {
+            match num::from_u64(x) {
+                Some(x) => Ok(x),
+                None => Err(OWERFLOW_ERROR.clone()),
+            }
+        })
+    }
+
+    fn read_le_length_delimited(&mut self, max: uint) -> IoResult<Vec<u8>> {
+        let len: uint = try!(self.read_le_varint());
+        if len > max {
+            return Err(LENGTH_EXCEEDS_MAXIMUM_ERROR.clone())
+        }
+        self.read_exact(len)
+    }
+
+    fn read_le_tag(&mut self) -> IoResult<(u32, u8)> {
+        let tag: u32 = try!(self.read_le_varint());
+        Ok((tag >> 3, (tag & 0x7) as u8))
+    }
 }
 
 
@@ -89,10 +204,55 @@ fn read_le_varint<R: Reader>(reader: &mut R) -> IoResult<u64> {
     unreachable!();
 }
 
+fn read_le_varint_strict<R: Reader>(reader: &mut R) -> IoResult<u64> {
+    let mut x = 0u64;
+    let mut shift = 0u;
+    for i in count(0u, 1) {
+        let b = try!(reader.read_byte());
+        if b < 0b1000_0000 {
+            if (i == 9 && b > 1) || i >= 10 {
+                return Err(OWERFLOW_ERROR.clone())
+            }
+            if i > 0 && b == 0 {
+                return Err(NON_MINIMAL_ENCODING_ERROR.clone())
+            }
+            return Ok(x | b as u64 << shift)
+        }
+        x |= (b as u64 & 0b0111_1111) << shift;
+        shift += 7;
+    }
+    unreachable!();
+}
+
 impl<W> VarintWriter for W where W: Writer {
     fn write_le_varint<V: Varint>(&mut self, x: V) -> IoResult<uint> {
         write_le_varint(self, x.to_u64().unwrap())
     }
+
+    fn write_le_signed_varint<V: SignedVarint>(&mut self, x: V) -> IoResult<uint> {
+        write_le_varint(self, zigzag_encode(x.to_i64().unwrap()))
+    }
+
+    fn write_le_length_delimited(&mut self, bytes: &[u8]) -> IoResult<uint> {
+        let n = try!(self.write_le_varint(bytes.len()));
+        try!(self.write(bytes));
+        Ok(n + bytes.len())
+    }
+
+    fn write_le_tag(&mut self, field_number: u32, wire_type: u8) -> IoResult<uint> {
+        if field_number > 0x1fffffff || wire_type > 5 {
+            return Err(INVALID_TAG_ERROR.clone())
+        }
+        self.write_le_varint((field_number << 3) | (wire_type as u32 & 0x7))
+    }
+
+    fn write_le_varint_slice<V: Varint + Copy>(&mut self, xs: &[V]) -> IoResult<uint> {
+        let size_hint = xs.iter().fold(0u, |acc, x| acc + x.varlen());
+        for &x in xs.iter() {
+            try!(self.write_le_varint(x));
+        }
+        Ok(size_hint)
+    }
 }
 
 fn write_le_varint<W: Writer>(writer: &mut W, mut x: u64) -> IoResult<uint> {
@@ -106,18 +266,65 @@ fn write_le_varint<W: Writer>(writer: &mut W, mut x: u64) -> IoResult<uint> {
     Ok(i + 1)
 }
 
+// Slice-based entry points for callers that already hold the bytes in
+// memory and don't want to pull in `std::io` (e.g. `no_std`/embedded use,
+// as with the `core_io`-based artiq-zynq port).
+pub fn encode_le_varint(x: u64, buf: &mut [u8]) -> Result<uint, ()> {
+    let mut x = x;
+    let mut i = 0u;
+    loop {
+        if i >= buf.len() {
+            return Err(())
+        }
+        if x >= 0b1000_0000 {
+            buf[i] = x as u8 | 0b1000_0000;
+            x >>= 7;
+            i += 1;
+        } else {
+            buf[i] = x as u8;
+            return Ok(i + 1)
+        }
+    }
+}
+
+pub fn decode_le_varint(buf: &[u8]) -> Result<(u64, uint), ()> {
+    let mut x = 0u64;
+    let mut shift = 0u;
+    for i in count(0u, 1) {
+        if i >= buf.len() {
+            return Err(())
+        }
+        let b = buf[i];
+        if b < 0b1000_0000 {
+            if (i == 9 && b > 1) || i >= 10 {
+                return Err(())
+            }
+            return Ok((x | b as u64 << shift, i + 1))
+        }
+        x |= (b as u64 & 0b0111_1111) << shift;
+        shift += 7;
+    }
+    unreachable!();
+}
+
 #[cfg(test)]
 mod test {
     use std::io::{BufReader, BufWriter, IoResult, OtherIoError};
     use std::fmt::Show;
 
-    use super::{VarintReader, VarintWriter, Varint, OWERFLOW_ERROR};
+    use super::{VarintReader, VarintWriter, Varint, SignedVarint, OWERFLOW_ERROR, NON_MINIMAL_ENCODING_ERROR,
+                LENGTH_EXCEEDS_MAXIMUM_ERROR, INVALID_TAG_ERROR, encode_le_varint, decode_le_varint};
 
     fn test_read_le_varint<V: Varint + PartialEq + Show>(buf: &[u8], expected: IoResult<V>) {
         let mut r = BufReader::new(buf);
         assert_eq!(r.read_le_varint(), expected);
     }
 
+    fn test_read_le_signed_varint<V: SignedVarint + PartialEq + Show>(buf: &[u8], expected: IoResult<V>) {
+        let mut r = BufReader::new(buf);
+        assert_eq!(r.read_le_signed_varint(), expected);
+    }
+
     #[test]
     fn read_le_varint() {
         test_read_le_varint([0x00], Ok(0x00u32));
@@ -134,6 +341,52 @@ mod test {
         test_read_le_varint::<u64>([0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x7F], Err(OWERFLOW_ERROR.clone()));
     }
 
+    #[test]
+    fn read_le_signed_varint() {
+        test_read_le_signed_varint([0x00], Ok(0i32));
+        test_read_le_signed_varint([0x01], Ok(-1i32));
+        test_read_le_signed_varint([0x02], Ok(1i32));
+        test_read_le_signed_varint([0x03], Ok(-2i32));
+        test_read_le_signed_varint([0x7F], Ok(-64i8));
+        test_read_le_signed_varint([0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01], Ok(-9223372036854775808i64));
+    }
+
+    #[test]
+    fn read_le_signed_varint_owerflow() {
+        test_read_le_signed_varint::<i8>([0xAC, 0x02], Err(OWERFLOW_ERROR.clone()));
+    }
+
+    fn test_read_le_varint_strict<V: Varint + PartialEq + Show>(buf: &[u8], expected: IoResult<V>) {
+        let mut r = BufReader::new(buf);
+        assert_eq!(r.read_le_varint_strict(), expected);
+    }
+
+    #[test]
+    fn read_le_varint_strict() {
+        test_read_le_varint_strict([0x00], Ok(0x00u32));
+        test_read_le_varint_strict([0x7F], Ok(0x7Fu32));
+        test_read_le_varint_strict([0x80, 0x01], Ok(0x80u32));
+        test_read_le_varint_strict([0xAC, 0x02], Ok(300u32));
+    }
+
+    #[test]
+    fn read_le_varint_strict_rejects_overlong_encoding() {
+        test_read_le_varint_strict::<u32>([0x80, 0x00], Err(NON_MINIMAL_ENCODING_ERROR.clone()));
+        test_read_le_varint_strict::<u32>([0x80, 0x80, 0x00], Err(NON_MINIMAL_ENCODING_ERROR.clone()));
+    }
+
+    #[test]
+    fn read_le_length_delimited() {
+        let mut r = BufReader::new([0x03, b'f', b'o', b'o']);
+        assert_eq!(r.read_le_length_delimited(10), Ok(vec![b'f', b'o', b'o']));
+    }
+
+    #[test]
+    fn read_le_length_delimited_rejects_length_over_max() {
+        let mut r = BufReader::new([0x03, b'f', b'o', b'o']);
+        assert_eq!(r.read_le_length_delimited(2), Err(LENGTH_EXCEEDS_MAXIMUM_ERROR.clone()));
+    }
+
     fn test_write_le_varint<V: Varint + PartialEq + Show>(x: V, result: &[u8]) {
         let mut buf = [0, ..10];
         let n = {
@@ -164,4 +417,104 @@ mod test {
             res => fail!(format!("{}", res)),
         }
     }
+
+    fn test_write_le_signed_varint<V: SignedVarint + PartialEq + Show>(x: V, result: &[u8]) {
+        let mut buf = [0, ..10];
+        let n = {
+            let mut r = BufWriter::new(&mut buf);
+            let r = r.write_le_signed_varint(x);
+            assert_eq!(Ok(result.len()), r);
+            r.unwrap()
+        };
+        assert_eq!(buf[.. n], result);
+    }
+
+    #[test]
+    fn write_le_signed_varint() {
+        test_write_le_signed_varint(0i32, [0x00]);
+        test_write_le_signed_varint(-1i32, [0x01]);
+        test_write_le_signed_varint(1i32, [0x02]);
+        test_write_le_signed_varint(-64i8, [0x7F]);
+        test_write_le_signed_varint(-9223372036854775808i64, [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01]);
+    }
+
+    #[test]
+    fn write_le_length_delimited() {
+        let mut buf = [0, ..4];
+        let n = {
+            let mut w = BufWriter::new(&mut buf);
+            w.write_le_length_delimited([b'f', b'o', b'o']).unwrap()
+        };
+        assert_eq!(n, 4);
+        assert_eq!(buf[.. n], [0x03, b'f', b'o', b'o']);
+    }
+
+    #[test]
+    fn encode_le_varint() {
+        let mut buf = [0, ..10];
+        assert_eq!(encode_le_varint(0x00, &mut buf), Ok(1));
+        assert_eq!(buf[.. 1], [0x00]);
+        assert_eq!(encode_le_varint(300, &mut buf), Ok(2));
+        assert_eq!(buf[.. 2], [0xAC, 0x02]);
+    }
+
+    #[test]
+    fn encode_le_varint_rejects_buffer_too_small() {
+        let mut buf = [0, ..1];
+        assert_eq!(encode_le_varint(300, &mut buf), Err(()));
+    }
+
+    #[test]
+    fn decode_le_varint() {
+        assert_eq!(decode_le_varint([0x00]), Ok((0x00u64, 1)));
+        assert_eq!(decode_le_varint([0xAC, 0x02]), Ok((300u64, 2)));
+        assert_eq!(decode_le_varint([0xAC, 0x02, 0xFF]), Ok((300u64, 2)));
+    }
+
+    #[test]
+    fn decode_le_varint_rejects_truncated_input() {
+        assert_eq!(decode_le_varint([0x80]), Err(()));
+    }
+
+    #[test]
+    fn read_le_tag() {
+        let mut r = BufReader::new([0x08]);
+        assert_eq!(r.read_le_tag(), Ok((1u32, 0u8)));
+    }
+
+    #[test]
+    fn write_le_tag() {
+        let mut buf = [0, ..1];
+        let n = {
+            let mut w = BufWriter::new(&mut buf);
+            w.write_le_tag(1, 0).unwrap()
+        };
+        assert_eq!(n, 1);
+        assert_eq!(buf[.. n], [0x08]);
+    }
+
+    #[test]
+    fn write_le_tag_rejects_out_of_range_wire_type() {
+        let mut buf = [0, ..1];
+        let mut w = BufWriter::new(&mut buf);
+        assert_eq!(w.write_le_tag(1, 6), Err(INVALID_TAG_ERROR.clone()));
+    }
+
+    #[test]
+    fn write_le_tag_rejects_out_of_range_field_number() {
+        let mut buf = [0, ..1];
+        let mut w = BufWriter::new(&mut buf);
+        assert_eq!(w.write_le_tag(0x20000000, 0), Err(INVALID_TAG_ERROR.clone()));
+    }
+
+    #[test]
+    fn write_le_varint_slice() {
+        let mut buf = [0, ..4];
+        let n = {
+            let mut w = BufWriter::new(&mut buf);
+            w.write_le_varint_slice([0x00u32, 0x7Fu32, 0x80u32]).unwrap()
+        };
+        assert_eq!(n, 4);
+        assert_eq!(buf[.. n], [0x00, 0x7F, 0x80, 0x01]);
+    }
 }